@@ -1,28 +1,119 @@
-use std::path::Path;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 extern crate cc;
 
 fn main() {
     let src_dir = Path::new("src");
 
+    for entry in fs::read_dir(src_dir).expect("src/ directory is required to build the grammar") {
+        let entry = entry.unwrap();
+        println!("cargo:rerun-if-changed={}", entry.path().to_str().unwrap());
+    }
+
+    println!("cargo:rerun-if-env-changed=TREE_SITTER_STATIC_ANALYSIS");
+    if let Some((clang_path, scan_build_path)) = static_analysis_tools() {
+        // `cc` splits `CC`/`CXX` on whitespace into a wrapper plus its
+        // default arguments, unlike `Build::compiler`, which treats its
+        // argument as a single executable path. Both scanner.c and
+        // scanner.cc need their respective variable set, since cc reads
+        // `CC` for C builds and `CXX` for C++ builds.
+        env::set_var(
+            "CC",
+            format!(
+                "{} -analyze-headers --use-analyzer={} cc",
+                scan_build_path.to_str().unwrap(),
+                clang_path.to_str().unwrap()
+            ),
+        );
+        env::set_var(
+            "CXX",
+            format!(
+                "{} -analyze-headers --use-analyzer={} c++",
+                scan_build_path.to_str().unwrap(),
+                clang_path.to_str().unwrap()
+            ),
+        );
+    }
+
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_ALLOCATION_TRACKING");
+    let allocation_tracking = env::var_os("CARGO_FEATURE_ALLOCATION_TRACKING").is_some();
+
+    let target = env::var("TARGET").unwrap();
+    let host = env::var("HOST").unwrap();
+
     let mut c_config = cc::Build::new();
     c_config.include(&src_dir);
+    // Intentionally not calling `.cargo_metadata(false)` here: it would also
+    // suppress the `rustc-link-lib`/`rustc-link-search` directives `cc`
+    // emits, which breaks linking for every consumer of this crate. Only
+    // warning output is meant to be silenced, hence `cargo_warnings(false)`
+    // alone.
+    c_config
+        .target(&target)
+        .host(&host)
+        .opt_level(2)
+        .cargo_warnings(false);
     c_config
         .flag_if_supported("-Wno-unused-parameter")
         .flag_if_supported("-Wno-unused-but-set-variable")
-        .flag_if_supported("-Wno-trigraphs");
+        .flag_if_supported("-Wno-trigraphs")
+        .flag_if_supported("-Werror=implicit-function-declaration");
+    if allocation_tracking {
+        c_config.define("TREE_SITTER_ALLOCATION_TRACKING", None);
+    }
     let parser_path = src_dir.join("parser.c");
     c_config.file(&parser_path);
-    println!("cargo:rerun-if-changed={}", parser_path.to_str().unwrap());
-    c_config.compile("parser");
 
-    let mut cpp_config = cc::Build::new();
-    cpp_config.cpp(true);
-    cpp_config.include(&src_dir);
-    cpp_config
-        .flag_if_supported("-Wno-unused-parameter")
-        .flag_if_supported("-Wno-unused-but-set-variable");
-    let scanner_path = src_dir.join("scanner.cc");
-    cpp_config.file(&scanner_path);
-    println!("cargo:rerun-if-changed={}", scanner_path.to_str().unwrap());
-    cpp_config.compile("scanner");
+    let scanner_c_path = src_dir.join("scanner.c");
+    let scanner_cc_path = src_dir.join("scanner.cc");
+
+    if scanner_c_path.exists() {
+        c_config.file(&scanner_c_path);
+        c_config.compile("parser");
+    } else {
+        c_config.compile("parser");
+
+        if scanner_cc_path.exists() {
+            let mut cpp_config = cc::Build::new();
+            cpp_config.cpp(true);
+            cpp_config.include(&src_dir);
+            cpp_config
+                .target(&target)
+                .host(&host)
+                .opt_level(2)
+                .cargo_warnings(false);
+            cpp_config
+                .flag_if_supported("-Wno-unused-parameter")
+                .flag_if_supported("-Wno-unused-but-set-variable")
+                .flag_if_supported("-Werror=implicit-function-declaration");
+            if allocation_tracking {
+                cpp_config.define("TREE_SITTER_ALLOCATION_TRACKING", None);
+            }
+            cpp_config.file(&scanner_cc_path);
+            cpp_config.compile("scanner");
+        }
+    }
+}
+
+/// Returns the `(clang, scan-build)` paths to wrap the compiler with when
+/// `TREE_SITTER_STATIC_ANALYSIS` is set, or `None` if the variable is unset
+/// or `clang`/`scan-build` aren't on `PATH`.
+fn static_analysis_tools() -> Option<(PathBuf, PathBuf)> {
+    env::var_os("TREE_SITTER_STATIC_ANALYSIS")?;
+    let clang_path = find_binary("clang")?;
+    let scan_build_path = find_binary("scan-build")?;
+    Some((clang_path, scan_build_path))
+}
+
+fn find_binary(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path).find_map(|dir| {
+        let candidate = dir.join(name);
+        if candidate.is_file() {
+            Some(candidate)
+        } else {
+            None
+        }
+    })
 }